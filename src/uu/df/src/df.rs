@@ -26,7 +26,7 @@ use std::mem;
 #[cfg(windows)]
 use std::path::Path;
 
-use crate::table::{DisplayRow, Header, Row};
+use crate::table::{Column, DisplayRow, Header, Row};
 
 static ABOUT: &str = "Show information about the file system on which each FILE resides,\n\
                       or all file systems by default.";
@@ -62,11 +62,14 @@ struct Options {
     show_local_fs: bool,
     show_all_fs: bool,
     show_listed_fs: bool,
-    show_fs_type: bool,
-    show_inode_instead: bool,
-    // block_size: usize,
+    show_total: bool,
     human_readable_base: i64,
+    block_size: BlockSize,
+    portability: bool,
+    sync_before: bool,
+    direct: bool,
     fs_selector: FsSelector,
+    columns: Vec<Column>,
 }
 
 impl Options {
@@ -76,8 +79,7 @@ impl Options {
             show_local_fs: matches.is_present(OPT_LOCAL),
             show_all_fs: matches.is_present(OPT_ALL),
             show_listed_fs: false,
-            show_fs_type: matches.is_present(OPT_PRINT_TYPE),
-            show_inode_instead: matches.is_present(OPT_INODES),
+            show_total: matches.is_present(OPT_TOTAL),
             human_readable_base: if matches.is_present(OPT_HUMAN_READABLE) {
                 1024
             } else if matches.is_present(OPT_HUMAN_READABLE_2) {
@@ -85,8 +87,190 @@ impl Options {
             } else {
                 -1
             },
+            block_size: BlockSize::from_matches(
+                matches,
+                if matches.is_present(OPT_PORTABILITY) {
+                    512
+                } else {
+                    1024
+                },
+            ),
+            portability: matches.is_present(OPT_PORTABILITY),
+            sync_before: matches.is_present(OPT_SYNC),
+            direct: matches.is_present(OPT_DIRECT),
             fs_selector: FsSelector::from(matches),
+            columns: columns_from(matches),
+        }
+    }
+}
+
+/// The unit, in bytes, that the block columns (`Size`, `Used`, `Avail`)
+/// are scaled by. Set from `-B`/`--block-size`, `-k`, or the
+/// `DF_BLOCK_SIZE`/`BLOCK_SIZE`/`BLOCKSIZE` environment variables, in
+/// that order of precedence; ignored when human-readable mode (`-h`/
+/// `-H`) is active instead.
+#[derive(Debug, Clone, Copy)]
+struct BlockSize {
+    bytes: u64,
+}
+
+impl Default for BlockSize {
+    fn default() -> Self {
+        Self { bytes: 1024 }
+    }
+}
+
+impl BlockSize {
+    /// Resolve the active block size from `-B`, `-k`, the
+    /// `DF_BLOCK_SIZE`/`BLOCK_SIZE`/`BLOCKSIZE` environment variables,
+    /// then `POSIXLY_CORRECT` (which forces 512), and finally
+    /// `default_bytes` (1024 normally, 512 for `-P`).
+    fn from_matches(matches: &ArgMatches, default_bytes: u64) -> Self {
+        if let Some(size) = matches.value_of(OPT_BLOCKSIZE) {
+            return Self::parse(size).unwrap_or_else(|| {
+                eprintln!(
+                    "{}: invalid --block-size argument '{}'",
+                    uucore::util_name(),
+                    size
+                );
+                std::process::exit(1);
+            });
+        }
+        if matches.is_present(OPT_KILO) {
+            return Self { bytes: 1024 };
+        }
+        for var in ["DF_BLOCK_SIZE", "BLOCK_SIZE", "BLOCKSIZE"] {
+            if let Ok(val) = std::env::var(var) {
+                if !val.is_empty() {
+                    if let Some(size) = Self::parse(&val) {
+                        return size;
+                    }
+                }
+            }
+        }
+        if std::env::var_os("POSIXLY_CORRECT").is_some() {
+            Self { bytes: 512 }
+        } else {
+            Self {
+                bytes: default_bytes,
+            }
+        }
+    }
+
+    /// Parse a SIZE like `4K`, `1MB`, `1GiB`, or a bare byte count.
+    ///
+    /// An optional integer multiplier is followed by an optional unit
+    /// suffix from `K,M,G,T,P,E,Z,Y`: the suffix alone, or with a
+    /// trailing `iB`, means powers of 1024; a trailing `B` (e.g. `KB`)
+    /// means powers of 1000.
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let split_at = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (digits, rest) = s.split_at(split_at);
+        let multiplier: u64 = if digits.is_empty() {
+            1
+        } else {
+            digits.parse().ok()?
+        };
+        if rest.is_empty() {
+            return Some(Self { bytes: multiplier });
+        }
+        let (unit_letter, base) = if let Some(unit) = rest.strip_suffix("iB") {
+            (unit, 1024u64)
+        } else if let Some(unit) = rest.strip_suffix('B') {
+            (unit, 1000u64)
+        } else {
+            (rest, 1024u64)
+        };
+        let mut chars = unit_letter.chars();
+        let unit = chars.next()?;
+        if chars.next().is_some() {
+            return None;
+        }
+        let power = match unit {
+            'K' => 1,
+            'M' => 2,
+            'G' => 3,
+            'T' => 4,
+            'P' => 5,
+            'E' => 6,
+            'Z' => 7,
+            'Y' => 8,
+            _ => return None,
+        };
+        let unit_bytes = base.checked_pow(power)?;
+        let bytes = multiplier.checked_mul(unit_bytes)?;
+        Some(Self { bytes })
+    }
+
+    /// The column header label for this block size, e.g. `1K-blocks` or
+    /// `512-blocks`.
+    fn label(&self) -> String {
+        // `1024^8`/`1024^7` (the Y/Z units) overflow `u64`, so these are
+        // computed with `checked_pow` and simply never match on a 64-bit
+        // block size rather than being baked into a `const` table.
+        const SUFFIXES: [&str; 8] = ["Y", "Z", "E", "P", "T", "G", "M", "K"];
+        for (power, suffix) in (1..=8u32).rev().zip(SUFFIXES) {
+            if let Some(unit) = 1024u64.checked_pow(power) {
+                if self.bytes % unit == 0 {
+                    return format!("{}{}-blocks", self.bytes / unit, suffix);
+                }
+            }
+        }
+        format!("{}-blocks", self.bytes)
+    }
+}
+
+/// Work out which [`Column`]s to display, and in what order.
+///
+/// `--output[=FIELD_LIST]` takes priority; `-i`, `-T` and `-P` are
+/// shorthands that preset specific field lists (`-i` composes with `-P`,
+/// swapping its block columns for inode columns, and with `-T`, adding
+/// the `Type` column), and a bare command line falls back to the GNU
+/// default list.
+fn columns_from(matches: &ArgMatches) -> Vec<Column> {
+    if matches.is_present(OPT_OUTPUT) {
+        match matches.values_of(OPT_OUTPUT) {
+            Some(fields) => {
+                let fields: Vec<String> = fields.map(ToString::to_string).collect();
+                if fields.is_empty() {
+                    Column::ALL.to_vec()
+                } else {
+                    fields
+                        .iter()
+                        .map(|field| {
+                            Column::from_str(field).unwrap_or_else(|| {
+                                eprintln!(
+                                    "{}: option --output: field '{}' is not a valid field name",
+                                    uucore::util_name(),
+                                    field
+                                );
+                                std::process::exit(1);
+                            })
+                        })
+                        .collect()
+                }
+            }
+            None => Column::ALL.to_vec(),
+        }
+    } else if matches.is_present(OPT_PORTABILITY) {
+        if matches.is_present(OPT_INODES) {
+            Column::POSIX_INODES.to_vec()
+        } else {
+            Column::DEFAULT.to_vec()
+        }
+    } else if matches.is_present(OPT_INODES) {
+        let mut columns = Column::INODES.to_vec();
+        if matches.is_present(OPT_PRINT_TYPE) {
+            columns.insert(1, Column::Fstype);
         }
+        columns
+    } else if matches.is_present(OPT_PRINT_TYPE) {
+        let mut columns = Column::DEFAULT.to_vec();
+        columns.insert(1, Column::Fstype);
+        columns
+    } else {
+        Column::DEFAULT.to_vec()
     }
 }
 
@@ -94,6 +278,16 @@ impl Options {
 struct Filesystem {
     mount_info: MountInfo,
     usage: FsUsage,
+    /// The literal FILE argument this filesystem was matched against, if
+    /// any FILE arguments were given on the command line. Used by
+    /// `--output=file`; falls back to the mount point when absent.
+    file: Option<String>,
+    /// The device name exactly as the mount table declared it, before
+    /// `UUID=`/`LABEL=`/`PARTUUID=` resolution. Kept around so a future
+    /// `--output=source` variant can still show what was declared even
+    /// though `mount_info.dev_name` is resolved to the real device.
+    #[allow(dead_code)]
+    dev_name_declared: String,
 }
 
 fn usage() -> String {
@@ -124,9 +318,38 @@ impl FsSelector {
     }
 }
 
+/// Resolve a `UUID=`/`LABEL=`/`PARTUUID=` mount-table entry, or an already
+/// symlinked `/dev/disk/by-*` path, to the real block device it points at.
+///
+/// Returns `None` (leaving the name untouched) when `dev_name` is not one
+/// of these forms, or when it is but doesn't resolve to anything, e.g. a
+/// stale mount table entry for a device that has since disappeared.
+#[cfg(unix)]
+fn resolve_dev_name(dev_name: &str) -> Option<String> {
+    let path = if let Some(uuid) = dev_name.strip_prefix("UUID=") {
+        std::path::Path::new("/dev/disk/by-uuid").join(uuid)
+    } else if let Some(label) = dev_name.strip_prefix("LABEL=") {
+        std::path::Path::new("/dev/disk/by-label").join(label)
+    } else if let Some(partuuid) = dev_name.strip_prefix("PARTUUID=") {
+        std::path::Path::new("/dev/disk/by-partuuid").join(partuuid)
+    } else if dev_name.starts_with("/dev/disk/by-") {
+        std::path::Path::new(dev_name).to_path_buf()
+    } else {
+        return None;
+    };
+    std::fs::canonicalize(path)
+        .ok()
+        .map(|resolved| resolved.to_string_lossy().into_owned())
+}
+
 impl Filesystem {
-    // TODO: resolve uuid in `mount_info.dev_name` if exists
-    fn new(mount_info: MountInfo) -> Option<Self> {
+    fn new(#[cfg_attr(windows, allow(unused_mut))] mut mount_info: MountInfo, file: Option<String>) -> Option<Self> {
+        let dev_name_declared = mount_info.dev_name.clone();
+        #[cfg(unix)]
+        if let Some(resolved) = resolve_dev_name(&mount_info.dev_name) {
+            mount_info.dev_name = resolved;
+        }
+
         let _stat_path = if !mount_info.mount_dir.is_empty() {
             mount_info.mount_dir.clone()
         } else {
@@ -150,6 +373,8 @@ impl Filesystem {
                 Some(Self {
                     mount_info,
                     usage: FsUsage::new(statvfs),
+                    file,
+                    dev_name_declared,
                 })
             }
         }
@@ -157,11 +382,97 @@ impl Filesystem {
         Some(Self {
             mount_info,
             usage: FsUsage::new(Path::new(&_stat_path)),
+            file,
+            dev_name_declared,
+        })
+    }
+
+    /// Build a [`Filesystem`] for `--direct`: stat `path` itself instead
+    /// of resolving it to a mount point first, so per-file filesystem
+    /// statistics are reported even when `path` is not itself a mount
+    /// point.
+    fn new_direct(path: &str) -> Option<Self> {
+        let mount_info = MountInfo {
+            dev_id: String::new(),
+            dev_name: path.to_string(),
+            fs_type: String::new(),
+            mount_root: String::new(),
+            mount_dir: path.to_string(),
+            mount_option: String::new(),
+            remote: false,
+            dummy: false,
+        };
+        #[cfg(unix)]
+        unsafe {
+            let cpath = CString::new(path).ok()?;
+            let mut statvfs = mem::zeroed();
+            if statfs_fn(cpath.as_ptr(), &mut statvfs) < 0 {
+                None
+            } else {
+                Some(Self {
+                    dev_name_declared: mount_info.dev_name.clone(),
+                    usage: FsUsage::new(statvfs),
+                    mount_info,
+                    file: Some(path.to_string()),
+                })
+            }
+        }
+        #[cfg(windows)]
+        Some(Self {
+            dev_name_declared: mount_info.dev_name.clone(),
+            usage: FsUsage::new(Path::new(path)),
+            mount_info,
+            file: Some(path.to_string()),
         })
     }
 }
 
-fn filter_mount_list(vmi: Vec<MountInfo>, paths: &[String], opt: &Options) -> Vec<MountInfo> {
+/// Flush filesystem buffers before sampling usage, as `--sync` requests.
+///
+/// `sync(2)` is resolved with `dlsym` rather than linked directly, the
+/// same trick the standard library's unix `fs` module uses for syscalls
+/// that may be missing on a given platform/libc: if the symbol can't be
+/// found we warn and fall through as a no-op instead of failing to run
+/// at all.
+#[cfg(unix)]
+fn sync_disks() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type SyncFn = unsafe extern "C" fn();
+    static ADDR: AtomicUsize = AtomicUsize::new(1);
+
+    unsafe fn lookup() -> usize {
+        let symbol = b"sync\0";
+        libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr() as *const libc::c_char) as usize
+    }
+
+    let mut addr = ADDR.load(Ordering::Relaxed);
+    if addr == 1 {
+        addr = unsafe { lookup() };
+        ADDR.store(addr, Ordering::Relaxed);
+    }
+    if addr == 0 {
+        eprintln!(
+            "{}: warning: sync is not supported on this platform; continuing without it",
+            uucore::util_name()
+        );
+    } else {
+        let sync_fn: SyncFn = unsafe { mem::transmute(addr) };
+        unsafe { sync_fn() };
+    }
+}
+
+/// Narrow the full mount table down to the entries `df` should report on.
+///
+/// Returns each surviving [`MountInfo`] paired with the literal FILE
+/// argument it was matched against, so that `--output=file` can later
+/// print what the user asked for rather than the mount point it resolved
+/// to.
+fn filter_mount_list(
+    vmi: Vec<MountInfo>,
+    paths: &[String],
+    opt: &Options,
+) -> Vec<(MountInfo, Option<String>)> {
     vmi.into_iter()
         .filter_map(|mi| {
             if (mi.remote && opt.show_local_fs)
@@ -172,24 +483,23 @@ fn filter_mount_list(vmi: Vec<MountInfo>, paths: &[String], opt: &Options) -> Ve
             } else {
                 if paths.is_empty() {
                     // No path specified
-                    return Some((mi.dev_id.clone(), mi));
+                    return Some((mi.dev_id.clone(), mi, None));
                 }
-                if paths.contains(&mi.mount_dir) {
+                match paths.iter().find(|&path| path == &mi.mount_dir) {
                     // One or more paths have been provided
-                    Some((mi.dev_id.clone(), mi))
-                } else {
+                    Some(path) => Some((mi.dev_id.clone(), mi, Some(path.clone()))),
                     // Not a path we want to see
-                    None
+                    None => None,
                 }
             }
         })
         .fold(
-            HashMap::<String, Cell<MountInfo>>::new(),
-            |mut acc, (id, mi)| {
+            HashMap::<String, Cell<(MountInfo, Option<String>)>>::new(),
+            |mut acc, (id, mi, file)| {
                 #[allow(clippy::map_entry)]
                 {
                     if acc.contains_key(&id) {
-                        let seen = acc[&id].replace(mi.clone());
+                        let (seen, seen_file) = acc[&id].replace((mi.clone(), file.clone()));
                         let target_nearer_root = seen.mount_dir.len() > mi.mount_dir.len();
                         // With bind mounts, prefer items nearer the root of the source
                         let source_below_root = !seen.mount_root.is_empty()
@@ -207,10 +517,10 @@ fn filter_mount_list(vmi: Vec<MountInfo>, paths: &[String], opt: &Options) -> Ve
                             environments for example.  */
                             || seen.mount_dir != mi.mount_dir)
                         {
-                            acc[&id].replace(seen);
+                            acc[&id].replace((seen, seen_file));
                         }
                     } else {
-                        acc.insert(id, Cell::new(mi));
+                        acc.insert(id, Cell::new((mi, file)));
                     }
                     acc
                 }
@@ -241,16 +551,45 @@ pub fn uumain(args: impl uucore::Args) -> UResult<()> {
 
     let opt = Options::from(&matches);
 
-    let mounts = read_fs_list();
-    let data: Vec<Row> = filter_mount_list(mounts, &paths, &opt)
-        .into_iter()
-        .filter_map(Filesystem::new)
-        .filter(|fs| fs.usage.blocks != 0 || opt.show_all_fs || opt.show_listed_fs)
-        .map(Into::into)
-        .collect();
-    println!("{}", Header::new(&opt));
-    for row in data {
-        println!("{}", DisplayRow::new(row, &opt));
+    if opt.sync_before {
+        #[cfg(unix)]
+        sync_disks();
+        #[cfg(windows)]
+        println!("{}: doesn't support --sync option", uucore::util_name());
+    }
+
+    let data: Vec<Row> = if opt.direct && !paths.is_empty() {
+        paths
+            .iter()
+            .filter_map(|path| Filesystem::new_direct(path))
+            .map(Into::into)
+            .collect()
+    } else {
+        let mounts = read_fs_list();
+        filter_mount_list(mounts, &paths, &opt)
+            .into_iter()
+            .filter_map(|(mi, file)| Filesystem::new(mi, file))
+            .filter(|fs| fs.usage.blocks != 0 || opt.show_all_fs || opt.show_listed_fs)
+            .map(Into::into)
+            .collect()
+    };
+    let total = if opt.show_total {
+        Some(Row::total(&data))
+    } else {
+        None
+    };
+    let mut rows_for_width: Vec<&Row> = data.iter().collect();
+    if let Some(total) = &total {
+        rows_for_width.push(total);
+    }
+    let widths = table::column_widths(&opt, &rows_for_width);
+
+    println!("{}", Header::new(&opt, &widths));
+    for row in &data {
+        println!("{}", DisplayRow::new(row, &opt, &widths));
+    }
+    if let Some(total) = &total {
+        println!("{}", DisplayRow::new(total, &opt, &widths));
     }
 
     Ok(())
@@ -305,6 +644,7 @@ pub fn uu_app<'a>() -> App<'a> {
             Arg::new(OPT_INODES)
                 .short('i')
                 .long("inodes")
+                .conflicts_with(OPT_OUTPUT)
                 .help("list inode information instead of block usage"),
         )
         .arg(Arg::new(OPT_KILO).short('k').help("like --block-size=1K"))
@@ -325,6 +665,8 @@ pub fn uu_app<'a>() -> App<'a> {
                 .long("output")
                 .takes_value(true)
                 .use_delimiter(true)
+                .min_values(0)
+                .conflicts_with_all(&[OPT_PORTABILITY, OPT_PRINT_TYPE])
                 .help(
                     "use the output format defined by FIELD_LIST,\
                      or print all fields if FIELD_LIST is omitted.",
@@ -334,6 +676,7 @@ pub fn uu_app<'a>() -> App<'a> {
             Arg::new(OPT_PORTABILITY)
                 .short('P')
                 .long("portability")
+                .conflicts_with(OPT_OUTPUT)
                 .help("use the POSIX output format"),
         )
         .arg(
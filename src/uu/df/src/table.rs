@@ -0,0 +1,415 @@
+// This file is part of the uutils coreutils package.
+//
+// (c) Fangxu Hu <framlog@gmail.com>
+// (c) Sylvestre Ledru <sylvestre@debian.org>
+//
+// For the full copyright and license information, please view the LICENSE file
+// that was distributed with this source code.
+
+//! The `--output`-selectable columns of the `df` table, and the
+//! [`Header`]/[`DisplayRow`] types that render them.
+
+use std::fmt;
+
+use crate::{Filesystem, Options};
+
+/// A single column that may appear in the `df` table.
+///
+/// The order in which these are listed here is the order GNU `df` uses
+/// when no `--output` list is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Column {
+    Source,
+    Fstype,
+    Itotal,
+    Iused,
+    Iavail,
+    Ipcent,
+    Size,
+    Used,
+    Avail,
+    Pcent,
+    File,
+    Target,
+}
+
+impl Column {
+    /// The default field list, used when `--output` is not given at all.
+    pub(crate) const DEFAULT: [Self; 6] = [
+        Self::Source,
+        Self::Size,
+        Self::Used,
+        Self::Avail,
+        Self::Pcent,
+        Self::Target,
+    ];
+
+    /// The full field list, used by a bare `--output` with no `FIELD_LIST`.
+    pub(crate) const ALL: [Self; 12] = [
+        Self::Source,
+        Self::Fstype,
+        Self::Itotal,
+        Self::Iused,
+        Self::Iavail,
+        Self::Ipcent,
+        Self::Size,
+        Self::Used,
+        Self::Avail,
+        Self::Pcent,
+        Self::File,
+        Self::Target,
+    ];
+
+    /// The field list implied by `-i`/`--inodes`.
+    pub(crate) const INODES: [Self; 6] = [
+        Self::Source,
+        Self::Itotal,
+        Self::Iused,
+        Self::Iavail,
+        Self::Ipcent,
+        Self::Target,
+    ];
+
+    /// The field list implied by `-P`/`--portability` together with `-i`.
+    pub(crate) const POSIX_INODES: [Self; 6] = [
+        Self::Source,
+        Self::Itotal,
+        Self::Iused,
+        Self::Iavail,
+        Self::Ipcent,
+        Self::Target,
+    ];
+
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        Some(match s {
+            "source" => Self::Source,
+            "fstype" => Self::Fstype,
+            "itotal" => Self::Itotal,
+            "iused" => Self::Iused,
+            "iavail" => Self::Iavail,
+            "ipcent" => Self::Ipcent,
+            "size" => Self::Size,
+            "used" => Self::Used,
+            "avail" => Self::Avail,
+            "pcent" => Self::Pcent,
+            "file" => Self::File,
+            "target" => Self::Target,
+            _ => return None,
+        })
+    }
+
+    /// The header text printed for this column, given the active options.
+    fn header(self, opt: &Options) -> String {
+        match self {
+            Self::Source => "Filesystem".to_string(),
+            Self::Fstype => "Type".to_string(),
+            Self::Itotal => "Inodes".to_string(),
+            Self::Iused => "IUsed".to_string(),
+            Self::Iavail => "IFree".to_string(),
+            Self::Ipcent => "IUse%".to_string(),
+            Self::Size => {
+                if opt.human_readable_base > 0 {
+                    "Size".to_string()
+                } else if opt.portability {
+                    // POSIX mode always spells out the literal block
+                    // count (e.g. `512-blocks`), never a `1K`-style
+                    // shorthand.
+                    format!("{}-blocks", opt.block_size.bytes)
+                } else {
+                    opt.block_size.label()
+                }
+            }
+            Self::Used => "Used".to_string(),
+            Self::Avail => {
+                if opt.portability {
+                    "Available".to_string()
+                } else {
+                    "Avail".to_string()
+                }
+            }
+            Self::Pcent => {
+                if opt.portability {
+                    "Capacity".to_string()
+                } else {
+                    "Use%".to_string()
+                }
+            }
+            Self::File => "File".to_string(),
+            Self::Target => "Mounted on".to_string(),
+        }
+    }
+
+    /// Whether this column is left-justified (text) rather than
+    /// right-justified (numeric), matching GNU `df`'s alignment.
+    fn is_left_aligned(self) -> bool {
+        matches!(self, Self::Source | Self::Fstype | Self::File | Self::Target)
+    }
+}
+
+/// The header row of the `df` table.
+pub(crate) struct Header<'a> {
+    opt: &'a Options,
+    widths: &'a [usize],
+}
+
+impl<'a> Header<'a> {
+    pub(crate) fn new(opt: &'a Options, widths: &'a [usize]) -> Self {
+        Self { opt, widths }
+    }
+}
+
+impl<'a> fmt::Display for Header<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let last = self.opt.columns.len().saturating_sub(1);
+        for (i, &column) in self.opt.columns.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write_cell(
+                f,
+                &column.header(self.opt),
+                *self.widths.get(i).unwrap_or(&0),
+                column.is_left_aligned(),
+                i == last,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `text` padded to `width` (left- or right-justified), except for
+/// a left-justified last column of a row, which is never padded so
+/// trailing whitespace doesn't leak into the output.
+fn write_cell(
+    f: &mut fmt::Formatter,
+    text: &str,
+    width: usize,
+    left_aligned: bool,
+    is_last: bool,
+) -> fmt::Result {
+    if is_last && left_aligned {
+        write!(f, "{}", text)
+    } else if left_aligned {
+        write!(f, "{:<width$}", text, width = width)
+    } else {
+        write!(f, "{:>width$}", text, width = width)
+    }
+}
+
+/// One data row of the `df` table: the fully-resolved values for a single
+/// filesystem, independent of which columns will actually be printed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Row {
+    /// The device/source (e.g. `/dev/sda1`), with `UUID=`/`LABEL=`/
+    /// `PARTUUID=` and `/dev/disk/by-*` names resolved to the real device.
+    pub(crate) fs_device: String,
+    pub(crate) fs_type: String,
+    /// The mount point this filesystem is attached at.
+    pub(crate) fs_mount: String,
+    /// The FILE argument this row was matched against, when one was given
+    /// on the command line (`--output=file`, `--direct`); falls back to
+    /// `fs_mount` when no FILE arguments were supplied.
+    pub(crate) file: String,
+
+    pub(crate) bytes: u64,
+    pub(crate) bytes_used: u64,
+    pub(crate) bytes_avail: u64,
+    pub(crate) bytes_usage: Option<f64>,
+
+    pub(crate) inodes: u64,
+    pub(crate) inodes_used: u64,
+    pub(crate) inodes_free: u64,
+    pub(crate) inodes_usage: Option<f64>,
+}
+
+impl From<Filesystem> for Row {
+    fn from(fs: Filesystem) -> Self {
+        let usage = &fs.usage;
+        let bytes = usage.blocks * usage.bsize;
+        let bytes_free = usage.bfree * usage.bsize;
+        let bytes_avail = usage.bavail * usage.bsize;
+        let bytes_used = bytes.saturating_sub(bytes_free);
+        let bytes_usage = percentage(bytes_used, bytes_used + bytes_avail);
+
+        let inodes = usage.files;
+        let inodes_free = usage.ffree;
+        let inodes_used = inodes.saturating_sub(inodes_free);
+        let inodes_usage = percentage(inodes_used, inodes);
+
+        Self {
+            fs_device: fs.mount_info.dev_name,
+            fs_type: fs.mount_info.fs_type,
+            fs_mount: fs.mount_info.mount_dir.clone(),
+            file: fs.file.unwrap_or(fs.mount_info.mount_dir),
+            bytes,
+            bytes_used,
+            bytes_avail,
+            bytes_usage,
+            inodes,
+            inodes_used,
+            inodes_free,
+            inodes_usage,
+        }
+    }
+}
+
+impl Row {
+    /// Build the synthetic grand-total row requested by `--total`.
+    ///
+    /// Byte and inode counts are summed across `rows`; the percentage
+    /// columns are recomputed from the summed used/available values
+    /// rather than averaged. `rows` is assumed to already be deduplicated
+    /// by device, as `filter_mount_list` does for the detail rows.
+    pub(crate) fn total(rows: &[Self]) -> Self {
+        let mut total = Self {
+            fs_device: "total".to_string(),
+            ..Self::default()
+        };
+        for row in rows {
+            total.bytes += row.bytes;
+            total.bytes_used += row.bytes_used;
+            total.bytes_avail += row.bytes_avail;
+            total.inodes += row.inodes;
+            total.inodes_used += row.inodes_used;
+            total.inodes_free += row.inodes_free;
+        }
+        total.bytes_usage = percentage(total.bytes_used, total.bytes_used + total.bytes_avail);
+        total.inodes_usage = percentage(total.inodes_used, total.inodes);
+        total
+    }
+}
+
+/// The percentage of `used` out of `total`, unrounded (callers pass
+/// `used + available` for blocks and the inode count for inodes; the
+/// printed value is rounded up by `pcent`); `None` when the total is zero
+/// (nothing to divide by).
+pub(crate) fn percentage(used: u64, total: u64) -> Option<f64> {
+    if total == 0 {
+        None
+    } else {
+        Some(100.0 * used as f64 / total as f64)
+    }
+}
+
+/// One rendered row of the `df` table, right/left-justified to `widths`.
+///
+/// `widths` must come from [`column_widths`] over the same `opt.columns`
+/// and the full set of rows being printed (including a `--total` row, if
+/// any), so every row in a table lines up under the header.
+pub(crate) struct DisplayRow<'a> {
+    row: &'a Row,
+    opt: &'a Options,
+    widths: &'a [usize],
+}
+
+impl<'a> DisplayRow<'a> {
+    pub(crate) fn new(row: &'a Row, opt: &'a Options, widths: &'a [usize]) -> Self {
+        Self { row, opt, widths }
+    }
+}
+
+/// The unpadded text for `column` of `row`, under the active `opt`.
+///
+/// Shared between [`column_widths`] (which needs every cell's length
+/// before anything is printed) and [`DisplayRow`] (which prints them).
+fn cell_text(column: Column, row: &Row, opt: &Options) -> String {
+    match column {
+        Column::Source => row.fs_device.clone(),
+        Column::Fstype => row.fs_type.clone(),
+        Column::Itotal => row.inodes.to_string(),
+        Column::Iused => row.inodes_used.to_string(),
+        Column::Iavail => row.inodes_free.to_string(),
+        Column::Ipcent => pcent(row.inodes_usage),
+        Column::Size => bytes_cell(row.bytes, opt),
+        Column::Used => bytes_cell(row.bytes_used, opt),
+        Column::Avail => bytes_cell(row.bytes_avail, opt),
+        Column::Pcent => pcent(row.bytes_usage),
+        Column::File => row.file.clone(),
+        Column::Target => row.fs_mount.clone(),
+    }
+}
+
+fn pcent(pcent: Option<f64>) -> String {
+    match pcent {
+        None => "-".to_string(),
+        Some(pcent) => format!("{}%", pcent.ceil() as u64),
+    }
+}
+
+/// Render a byte count for a block column, honoring human-readable mode
+/// or, failing that, the active block size.
+fn bytes_cell(bytes: u64, opt: &Options) -> String {
+    if opt.human_readable_base > 0 {
+        human_readable(bytes, opt.human_readable_base as u64)
+    } else {
+        div_ceil(bytes, opt.block_size.bytes).to_string()
+    }
+}
+
+/// The printed width of each column in `opt.columns`, i.e. the widest of
+/// its header and every row's rendered cell, so [`Header`] and
+/// [`DisplayRow`] can right/left-justify block and text columns the way
+/// GNU `df` does.
+pub(crate) fn column_widths(opt: &Options, rows: &[&Row]) -> Vec<usize> {
+    opt.columns
+        .iter()
+        .map(|&column| {
+            let header_width = column.header(opt).len();
+            rows.iter()
+                .map(|row| cell_text(column, row, opt).len())
+                .fold(header_width, std::cmp::max)
+        })
+        .collect()
+}
+
+/// Divide `n` by `d`, rounding up, as `df` does when scaling block counts.
+fn div_ceil(n: u64, d: u64) -> u64 {
+    if d == 0 {
+        0
+    } else {
+        (n + d - 1) / d
+    }
+}
+
+/// Format `bytes` as a human-readable size (e.g. `1.2G`), scaling by
+/// `base` (1024 for `-h`, 1000 for `-H`/`--si`), rounding up so the
+/// result never under-reports.
+fn human_readable(bytes: u64, base: u64) -> String {
+    const SUFFIXES: [&str; 9] = ["", "K", "M", "G", "T", "P", "E", "Z", "Y"];
+    let mut value = bytes as f64;
+    let mut suffix = SUFFIXES[0];
+    for &s in &SUFFIXES[1..] {
+        if value < base as f64 {
+            break;
+        }
+        value /= base as f64;
+        suffix = s;
+    }
+    if suffix.is_empty() {
+        format!("{}", value.ceil() as u64)
+    } else if value < 10.0 {
+        format!("{:.1}{}", (value * 10.0).ceil() / 10.0, suffix)
+    } else {
+        format!("{}{}", value.ceil() as u64, suffix)
+    }
+}
+
+impl<'a> fmt::Display for DisplayRow<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let last = self.opt.columns.len().saturating_sub(1);
+        for (i, &column) in self.opt.columns.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            let text = cell_text(column, self.row, self.opt);
+            write_cell(
+                f,
+                &text,
+                *self.widths.get(i).unwrap_or(&0),
+                column.is_left_aligned(),
+                i == last,
+            )?;
+        }
+        Ok(())
+    }
+}